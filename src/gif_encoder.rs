@@ -4,7 +4,11 @@ use derivative::Derivative;
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 
-use crate::{lzw_encoder::LZWEncoder, neuquant::NeuQuant};
+use crate::{
+	lzw_encoder::LZWEncoder,
+	neuquant::NeuQuant,
+	palette::{Elbg, MedianCut, Quantizer},
+};
 
 const GIF_HEADER: &[u8] = "GIF89a".as_bytes();
 const NETSCAPE_HEADER: [u8; 11] =
@@ -12,10 +16,207 @@ const NETSCAPE_HEADER: [u8; 11] =
 
 // Color table size (bits - 1)
 const PALETTE_SIZE: usize = 7;
+const MAX_PALETTE_ENTRIES: usize = 1 << (PALETTE_SIZE + 1);
+
+const IMAGE_SEPARATOR: u8 = 0x2c;
+const TRAILER: u8 = 0x3b;
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const APPLICATION_EXTENSION_LABEL: u8 = 0xff;
+const GRAPHIC_CONTROL_LABEL: u8 = 0xf9;
+const GLOBAL_COLOR_TABLE_FLAG: u8 = 0b1000_0000;
+const LOCAL_COLOR_TABLE_FLAG: u8 = 0b1000_0000;
+const TRANSPARENT_COLOR_FLAG: u8 = 0b0000_0001;
+
+// MS Video1-style skip threshold: quality 100 keeps every pixel (threshold
+// 0), quality 0 tolerates the widest per-channel drift before a pixel counts
+// as "changed".
+const SKIP_THRESHOLD_SCALE: u8 = 3;
+
+fn quality_skip_threshold(quality: u32) -> u8 {
+	let level = u32::min(quality / 10, 10);
+	(10 - level) as u8 * SKIP_THRESHOLD_SCALE
+}
+
+// Starting NeuQuant `sample` factor for a quality level: 100 asks for the
+// finest (slowest) sampling, 0 for the coarsest.
+fn quality_to_sample(quality: u32) -> u16 {
+	let level = u32::min(quality, 100);
+	(1 + (100 - level) * (MAX_SAMPLE as u32 - 1) / 100) as u16
+}
+
+// NeuQuant/MedianCut/Elbg all work over (b, g, r) triples; convert from the
+// RGBA buffers `add_frame` receives.
+fn to_bgr_triples(pixels: &[u8]) -> Vec<u8> {
+	let mut triples = Vec::with_capacity(pixels.len() / 4 * 3);
+	for rgba in pixels.chunks_exact(4) {
+		triples.extend_from_slice(&[rgba[2], rgba[1], rgba[0]]);
+	}
+	triples
+}
+
+// Writes `2 ^ color_depth` (r, g, b) entries, padding unused trailing slots
+// with black the way GIF color tables expect a power-of-two size.
+fn write_color_table(output: &mut BytesMut, palette: &[f64], color_depth: u8) {
+	let entries = 1usize << color_depth;
+	for i in 0..entries {
+		let base = i * 3;
+		let (b, g, r) = if base + 2 < palette.len() {
+			(palette[base], palette[base + 1], palette[base + 2])
+		} else {
+			(0.0, 0.0, 0.0)
+		};
+		output.put_u8(r as u8);
+		output.put_u8(g as u8);
+		output.put_u8(b as u8);
+	}
+}
+
+/// Bounding box of the pixels that changed between two frames, in the
+/// coordinate space of the full logical screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DirtyRect {
+	left: u16,
+	top: u16,
+	width: u16,
+	height: u16,
+}
+
+impl DirtyRect {
+	fn full(width: u16, height: u16) -> Self {
+		DirtyRect { left: 0, top: 0, width, height }
+	}
+}
+
+// 4x4 Bayer matrix used for ordered dithering, thresholds 0..15.
+const BAYER_4X4: [[i16; 4]; 4] = [
+	[0, 8, 2, 10],
+	[12, 4, 14, 6],
+	[3, 11, 1, 9],
+	[15, 7, 13, 5],
+];
+
+/// How `GifEncoder` should smooth banding when mapping pixels down to a
+/// 256-color palette.
+#[napi]
+#[derive(Derivative, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum DitherMode {
+	#[derivative(Default)]
+	Off,
+	Ordered,
+	ErrorDiffusion,
+}
+
+// Mean squared per-pixel distance above which `PaletteScope::Auto` decides a
+// frame is different enough from the running global palette to deserve its
+// own local color table.
+const LOCAL_PALETTE_DISTORTION_THRESHOLD: f64 = 400.0;
+
+/// Whether `GifEncoder` writes one color table for the whole animation or
+/// lets individual frames carry their own.
+#[napi]
+#[derive(Derivative, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum PaletteScope {
+	#[derivative(Default)]
+	Global,
+	Local,
+	Auto,
+}
+
+// Mean squared distance between `pixels` and their nearest color in
+// `palette`, used to decide whether a frame's colors have drifted too far
+// from the running global table. `stride` skips pixels the same way
+// NeuQuant's `sample_factorial` trades accuracy for speed, since this runs
+// against every frame under `PaletteScope::Auto`.
+fn quantization_distortion(pixels: &[u8], palette: &[f64], stride: usize) -> f64 {
+	if palette.is_empty() {
+		return f64::INFINITY;
+	}
+
+	let mut total = 0f64;
+	let mut count = 0usize;
+
+	for rgba in pixels.chunks_exact(4).step_by(stride.max(1)) {
+		let (b, g, r) = (rgba[2] as i16, rgba[1] as i16, rgba[0] as i16);
+		let index = nearest_palette_index(palette, b, g, r);
+		let chosen = &palette[index as usize * 3..index as usize * 3 + 3];
+
+		total += (b as f64 - chosen[0]).powi(2)
+			+ (g as f64 - chosen[1]).powi(2)
+			+ (r as f64 - chosen[2]).powi(2);
+		count += 1;
+	}
+
+	total / count.max(1) as f64
+}
+
+// Minimum bits needed to index `color_count` palette entries, which is what
+// both `color_depth` and the Image Descriptor's local-color-table size field
+// expect.
+fn color_depth_for_palette(color_count: usize) -> u8 {
+	let bits = usize::BITS - (color_count.max(1) - 1).leading_zeros();
+	(bits as u8).max(2)
+}
+
+// Bits needed to cover every index this frame's `idxed_pixels` actually uses
+// (including whatever transparent index diffing assigned), rather than the
+// quantizer's fixed, black-padded table length. Quantizers always hand back
+// a `[f64; 256 * 3]`-shaped map, so sizing the color table off its raw
+// length wastes bits on every frame with fewer than 256 real colors.
+fn color_depth_for_indices(idxed_pixels: &[u8]) -> u8 {
+	let highest = idxed_pixels.iter().copied().max().unwrap_or(0);
+	color_depth_for_palette(highest as usize + 1)
+}
+
+// Valid ranges for the parameters the rate controller is allowed to tune.
+const MIN_SAMPLE: u16 = 1;
+const MAX_SAMPLE: u16 = 30;
+const MAX_SKIP_THRESHOLD: u8 = 10 * SKIP_THRESHOLD_SCALE;
+
+// Below this fractional error, the controller leaves parameters alone rather
+// than hunting around the target forever.
+const RATE_CONTROL_DEADBAND: f64 = 0.05;
+const RATE_CONTROL_SAMPLE_GAIN: f64 = 10.0;
+const RATE_CONTROL_THRESHOLD_GAIN: f64 = 15.0;
+
+// Sum-of-abs nearest neighbour search against a `[f64; 256 * 3]`-shaped
+// palette, matching the distance metric `NeuQuant::contest` uses internally.
+fn nearest_palette_index(palette: &[f64], b: i16, g: i16, r: i16) -> u8 {
+	palette
+		.chunks_exact(3)
+		.enumerate()
+		.min_by_key(|(_, color)| {
+			(color[0] as i32 - b as i32).unsigned_abs()
+				+ (color[1] as i32 - g as i32).unsigned_abs()
+				+ (color[2] as i32 - r as i32).unsigned_abs()
+		})
+		.map(|(i, _)| i as u8)
+		.unwrap_or(0)
+}
+
+// Adds a weighted Floyd-Steinberg residual into a pending-error row, a no-op
+// past either edge of the frame.
+fn add_diffused_error(
+	row: &mut [[i16; 3]],
+	x: isize,
+	width: usize,
+	residual: [i16; 3],
+	weight: i16,
+) {
+	if x < 0 || x as usize >= width {
+		return;
+	}
+
+	let x = x as usize;
+	for c in 0..3 {
+		row[x][c] += residual[c] * weight / 16;
+	}
+}
 
 // TODO: make PALETTE_SIZE and DispoalCode u3?
 #[napi]
-#[derive(Derivative)]
+#[derive(Derivative, Clone, Copy, PartialEq, Eq)]
 #[derivative(Default)]
 pub enum DisposalCode {
 	#[derivative(Default)]
@@ -45,6 +246,10 @@ pub struct EncoderOpts {
 	pub repeat: i32,
 	pub transparent: Option<i32>,
 	pub quality: u32,
+	pub quantizer: Quantizer,
+	pub dither: DitherMode,
+	pub palette_scope: PaletteScope,
+	pub target_bytes: Option<i64>,
 }
 
 #[napi]
@@ -63,11 +268,18 @@ pub struct GifEncoder {
 	color_palette: Option<Vec<f64>>,
 	used_entry: BitVec,
 	disposal_mode: DisposalCode,
+	quantizer: Quantizer,
 	first_frame: bool,
 	sample: u16,
 	started: bool,
 	readable_streams: Vec<Buffer>,
 	byte_buf: BytesMut,
+	previous_pixels: Option<BytesMut>,
+	skip_threshold: u8,
+	dither: DitherMode,
+	palette_scope: PaletteScope,
+	target_bytes: Option<i64>,
+	frame_sizes: Vec<u32>,
 }
 
 #[napi]
@@ -77,13 +289,206 @@ impl GifEncoder {
 		Self { width, height, ..Self::default() }
 	}
 
+	/// Starts the animation: writes the `GIF89a` header and records the
+	/// encode options. Must be called before the first [`Self::add_frame`].
 	#[napi]
-	pub fn create_read_stream(&self) -> Buffer {
-		unimplemented!()
+	pub fn start(&mut self, opts: EncoderOpts) -> Result<()> {
+		self.delay = opts.delay / 10;
+		self.repeat = opts.repeat;
+		self.disposal_mode = opts.dispose;
+		self.transparent = opts.transparent.map(|t| t as f64);
+		self.quantizer = opts.quantizer;
+		self.dither = opts.dither;
+		self.palette_scope = opts.palette_scope;
+		self.target_bytes = opts.target_bytes;
+		self.skip_threshold = quality_skip_threshold(opts.quality);
+		self.sample = quality_to_sample(opts.quality);
+		self.first_frame = true;
+		self.started = true;
+
+		self.byte_buf.extend_from_slice(GIF_HEADER);
+		self.flush();
+		Ok(())
+	}
+
+	/// Quantizes `pixels` (a tightly-packed RGBA buffer) against the active
+	/// quantizer, diffs it against the previous frame, and appends the
+	/// resulting Image Descriptor and LZW data to the encoded stream.
+	#[napi]
+	pub fn add_frame(&mut self, pixels: Buffer) -> Result<()> {
+		if !self.started {
+			return Err(Error::from_reason(
+				"add_frame called before start",
+			));
+		}
+
+		let pixels: Vec<u8> = pixels.to_vec();
+		let use_local = self.should_use_local_palette(&pixels);
+		let local_map = use_local.then(|| self.build_color_map(&pixels));
+
+		let palette = match (&local_map, &self.color_palette) {
+			(Some(local), _) => local.clone(),
+			(None, Some(global)) => global.clone(),
+			(None, None) => {
+				let map = self.build_color_map(&pixels);
+				self.color_palette = Some(map.clone());
+				map
+			}
+		};
+
+		// Keep a running "global" baseline for `PaletteScope::Auto` to compare
+		// against even when this particular frame ends up using its own Local
+		// Color Table instead.
+		if self.color_palette.is_none() {
+			self.color_palette = local_map;
+		}
+
+		let palette_colors = palette.len() / 3;
+		let mut idxed_pixels = self.quantize_with_dither(&pixels, &palette);
+		let transparent_idx =
+			self.ensure_transparent_index(&idxed_pixels, palette_colors);
+
+		let rect = match (self.disposal_mode, transparent_idx) {
+			(DisposalCode::NoDispose, Some(idx)) => {
+				self.transparent_idx = idx;
+				self.diff_against_previous(&pixels, &mut idxed_pixels)
+			}
+			_ => DirtyRect::full(self.width as u16, self.height as u16),
+		};
+		let cropped = self.crop_to_rect(&idxed_pixels, rect);
+
+		let mut frame = BytesMut::new();
+
+		if self.first_frame {
+			// The Global Color Table is written once and reused verbatim by
+			// every later global-scope frame, which can land on any index the
+			// palette (and the transparent slot reserved out of it) covers —
+			// not just the indices frame 0 happens to use. Size it off the
+			// full palette instead of this frame's usage, unlike the
+			// per-frame Local Color Table below.
+			self.color_depth = color_depth_for_palette(palette_colors);
+			self.write_logical_screen_descriptor(&mut frame, self.color_depth);
+			write_color_table(&mut frame, &palette, self.color_depth);
+			self.write_netscape_extension(&mut frame);
+		}
+
+		// A Local Color Table only ever describes this one frame, so it's
+		// safe to size it off the indices this frame actually produced.
+		let local_depth =
+			use_local.then(|| color_depth_for_indices(&idxed_pixels));
+
+		self.write_graphic_control_extension(&mut frame, transparent_idx);
+		self.write_image_descriptor(&mut frame, rect, local_depth);
+		if let Some(depth) = local_depth {
+			write_color_table(&mut frame, &palette, depth);
+		}
+
+		let frame_color_depth = local_depth.unwrap_or(self.color_depth);
+		let mut lzw =
+			LZWEncoder::new(rect.width, rect.height, cropped, frame_color_depth);
+		lzw.encode(&mut frame);
+
+		self.byte_buf.extend_from_slice(&frame);
+		self.record_frame_size(frame.len());
+
+		self.previous_pixels = Some(BytesMut::from(pixels.as_slice()));
+		self.first_frame = false;
+
+		self.flush();
+		Ok(())
+	}
+
+	/// Appends the GIF trailer and flushes any remaining bytes.
+	#[napi]
+	pub fn finish(&mut self) -> Result<()> {
+		self.byte_buf.put_u8(TRAILER);
+		self.flush();
+		Ok(())
+	}
+
+	/// Drains and returns whatever encoded chunks have been produced since
+	/// the last call, so callers can pipe large animations to a file or
+	/// socket without holding the full output in memory.
+	#[napi]
+	pub fn create_read_stream(&mut self) -> Vec<Buffer> {
+		std::mem::take(&mut self.readable_streams)
+	}
+
+	// There's no writable-stream counterpart to `create_read_stream`: `start`
+	// and `add_frame` already take pushed frames incrementally and write
+	// straight into `byte_buf`, so they're the "writable" half of this
+	// pipeline. A separate `create_write_stream` had no caller and nothing
+	// queued to hand back, so it's dropped rather than left panicking.
+
+	// Moves whatever has accumulated in `byte_buf` into `readable_streams` as
+	// a new chunk ready for `create_read_stream` to hand off.
+	fn flush(&mut self) {
+		if self.byte_buf.is_empty() {
+			return;
+		}
+
+		let chunk = self.byte_buf.split();
+		self.readable_streams.push(chunk.to_vec().into());
+	}
+
+	fn build_color_map(&self, pixels: &[u8]) -> Vec<f64> {
+		let triples = to_bgr_triples(pixels);
+
+		match self.quantizer {
+			Quantizer::NeuQuant => {
+				NeuQuant::new(triples, self.sample.clamp(1, u8::MAX as u16) as u8)
+					.get_color_map()
+					.to_vec()
+			}
+			Quantizer::MedianCut => {
+				MedianCut::new(triples, MAX_PALETTE_ENTRIES as u16)
+					.get_color_map()
+					.to_vec()
+			}
+			Quantizer::Elbg => {
+				let seed =
+					MedianCut::new(triples.clone(), MAX_PALETTE_ENTRIES as u16);
+				let seed_map = seed.get_color_map().to_vec();
+				let seed_count = seed.color_count();
+				Elbg::new(triples, seed_map, seed_count).get_color_map().to_vec()
+			}
+		}
+	}
+
+	fn write_logical_screen_descriptor(&self, output: &mut BytesMut, color_depth: u8) {
+		output.put_u16_le(self.width as u16);
+		output.put_u16_le(self.height as u16);
+		output.put_u8(
+			GLOBAL_COLOR_TABLE_FLAG | ((color_depth - 1) << 4) | (color_depth - 1),
+		);
+		output.put_u8(0); // Background color index
+		output.put_u8(0); // Pixel aspect ratio
+	}
+
+	fn write_netscape_extension(&self, output: &mut BytesMut) {
+		output.put_u8(EXTENSION_INTRODUCER);
+		output.put_u8(APPLICATION_EXTENSION_LABEL);
+		output.put_u8(NETSCAPE_HEADER.len() as u8);
+		output.extend_from_slice(&NETSCAPE_HEADER);
+		output.put_u8(3); // Sub-block size
+		output.put_u8(1); // Loop sub-block id
+		output.put_u16_le(self.repeat.max(0) as u16);
+		output.put_u8(0); // Block terminator
 	}
 
-	pub fn create_write_stream(&self, opts: EncoderOpts) -> Buffer {
-		unimplemented!()
+	fn write_graphic_control_extension(
+		&self,
+		output: &mut BytesMut,
+		transparent_idx: Option<usize>,
+	) {
+		output.put_u8(EXTENSION_INTRODUCER);
+		output.put_u8(GRAPHIC_CONTROL_LABEL);
+		output.put_u8(4); // Block size
+		let flag = if transparent_idx.is_some() { TRANSPARENT_COLOR_FLAG } else { 0 };
+		output.put_u8(((self.disposal_mode as u8) << 2) | flag);
+		output.put_u16_le(self.delay as u16);
+		output.put_u8(transparent_idx.unwrap_or(0) as u8);
+		output.put_u8(0); // Block terminator
 	}
 
 	#[napi]
@@ -97,4 +502,290 @@ impl GifEncoder {
 		self.delay = 100 / fps;
 		self
 	}
+
+	/// Emitted byte length of every frame encoded so far, in order. Useful
+	/// for logging how the `target_bytes` rate controller behaved.
+	#[napi]
+	pub fn frame_sizes(&self) -> Vec<u32> {
+		self.frame_sizes.clone()
+	}
+
+	// Records how large a frame came out and, if `target_bytes` is set,
+	// nudges `sample`/`skip_threshold` the opposite direction of the miss so
+	// the next frame starts from a better guess. A proportional controller
+	// rather than a search. The frame that missed is not re-encoded; the
+	// adjustment only takes effect from the next frame on.
+	fn record_frame_size(&mut self, encoded_len: usize) {
+		self.frame_sizes.push(encoded_len as u32);
+
+		let Some(target) = self.target_bytes else { return };
+		if target <= 0 {
+			return;
+		}
+
+		let target = target as f64;
+		let error = (encoded_len as f64 - target) / target;
+		if error.abs() < RATE_CONTROL_DEADBAND {
+			return;
+		}
+
+		let sample_adjustment = (error * RATE_CONTROL_SAMPLE_GAIN).round() as i32;
+		self.sample = (self.sample as i32 + sample_adjustment)
+			.clamp(MIN_SAMPLE as i32, MAX_SAMPLE as i32) as u16;
+
+		let threshold_adjustment =
+			(error * RATE_CONTROL_THRESHOLD_GAIN).round() as i32;
+		self.skip_threshold = (self.skip_threshold as i32 + threshold_adjustment)
+			.clamp(0, MAX_SKIP_THRESHOLD as i32) as u8;
+	}
+
+	// Picks the transparent palette index for a frame already quantized
+	// against `palette`, honoring an explicit `transparent` option. Otherwise,
+	// only meaningful when `DisposalCode::NoDispose` is in play (the
+	// dirty-rectangle pass needs somewhere to point skipped pixels at): marks
+	// every index `idxed_pixels` actually uses in `used_entry` and claims the
+	// first one left over, returning `None` if the palette is already full.
+	fn ensure_transparent_index(
+		&mut self,
+		idxed_pixels: &[u8],
+		palette_len: usize,
+	) -> Option<usize> {
+		if let Some(explicit) = self.transparent {
+			return Some((explicit as usize).min(palette_len.saturating_sub(1)));
+		}
+
+		if self.disposal_mode != DisposalCode::NoDispose {
+			return None;
+		}
+
+		self.used_entry.clear();
+		self.used_entry.resize(palette_len, false);
+		for &idx in idxed_pixels {
+			if (idx as usize) < palette_len {
+				self.used_entry.set(idx as usize, true);
+			}
+		}
+
+		self.used_entry.iter().position(|used| !*used)
+	}
+
+	// Compares `pixels` (RGBA, full frame) against the previously emitted
+	// frame, marking every pixel whose largest per-channel difference stays
+	// under `skip_threshold` with the transparent index (so, combined
+	// with `DisposalCode::NoDispose`, the decoder keeps showing the prior
+	// pixel there) and returns the bounding box of whatever actually
+	// changed.
+	fn diff_against_previous(
+		&self,
+		pixels: &[u8],
+		idxed_pixels: &mut [u8],
+	) -> DirtyRect {
+		let width = self.width as usize;
+		let height = self.height as usize;
+
+		let Some(previous) = &self.previous_pixels else {
+			return DirtyRect::full(width as u16, height as u16);
+		};
+
+		let transparent_idx = self.transparent_idx as u8;
+		let (mut left, mut top) = (width, height);
+		let (mut right, mut bottom) = (0usize, 0usize);
+
+		for y in 0..height {
+			for x in 0..width {
+				let pixel_idx = (y * width + x) * 4;
+				let diff = (0..3)
+					.map(|c| {
+						(pixels[pixel_idx + c] as i16
+							- previous[pixel_idx + c] as i16)
+							.unsigned_abs()
+					})
+					.max()
+					.unwrap_or(0);
+
+				if diff < self.skip_threshold as u16 {
+					idxed_pixels[y * width + x] = transparent_idx;
+					continue;
+				}
+
+				left = left.min(x);
+				right = right.max(x);
+				top = top.min(y);
+				bottom = bottom.max(y);
+			}
+		}
+
+		if right < left || bottom < top {
+			// Nothing changed; still need a valid (if minimal) descriptor.
+			return DirtyRect { left: 0, top: 0, width: 1, height: 1 };
+		}
+
+		DirtyRect {
+			left: left as u16,
+			top: top as u16,
+			width: (right - left + 1) as u16,
+			height: (bottom - top + 1) as u16,
+		}
+	}
+
+	// Crops a full-frame index buffer down to just the dirty rectangle so
+	// only the changed pixels are fed into the LZW encoder.
+	fn crop_to_rect(&self, idxed_pixels: &[u8], rect: DirtyRect) -> BytesMut {
+		let width = self.width as usize;
+		let mut cropped = BytesMut::with_capacity(rect.width as usize * rect.height as usize);
+
+		for y in 0..rect.height as usize {
+			let row = (rect.top as usize + y) * width + rect.left as usize;
+			cropped.extend_from_slice(
+				&idxed_pixels[row..row + rect.width as usize],
+			);
+		}
+
+		cropped
+	}
+
+	// `local_color_depth` is `Some(bits)` when this frame carries its own
+	// Local Color Table, sized to `2 ^ bits` entries.
+	fn write_image_descriptor(
+		&self,
+		output: &mut BytesMut,
+		rect: DirtyRect,
+		local_color_depth: Option<u8>,
+	) {
+		output.put_u8(IMAGE_SEPARATOR);
+		output.put_u16_le(rect.left);
+		output.put_u16_le(rect.top);
+		output.put_u16_le(rect.width);
+		output.put_u16_le(rect.height);
+
+		let packed = match local_color_depth {
+			Some(depth) => LOCAL_COLOR_TABLE_FLAG | (depth - 1),
+			None => 0,
+		};
+		output.put_u8(packed);
+	}
+
+	// Decides whether `pixels` should be quantized against its own Local
+	// Color Table rather than the running global one.
+	fn should_use_local_palette(&self, pixels: &[u8]) -> bool {
+		match self.palette_scope {
+			PaletteScope::Global => false,
+			PaletteScope::Local => true,
+			PaletteScope::Auto => match &self.color_palette {
+				None => true,
+				Some(global) => {
+					quantization_distortion(pixels, global, self.sample as usize)
+						> LOCAL_PALETTE_DISTORTION_THRESHOLD
+				}
+			},
+		}
+	}
+
+	// Maps RGBA `pixels` to palette indices according to `self.dither`,
+	// against whichever `palette` the frame is quantizing against (the
+	// running global one, or a frame-local table).
+	fn quantize_with_dither(&self, pixels: &[u8], palette: &[f64]) -> BytesMut {
+		match self.dither {
+			DitherMode::Off => self.quantize_nearest(pixels, palette),
+			DitherMode::Ordered => self.dither_ordered(pixels, palette),
+			DitherMode::ErrorDiffusion => {
+				self.dither_error_diffusion(pixels, palette)
+			}
+		}
+	}
+
+	fn quantize_nearest(&self, pixels: &[u8], palette: &[f64]) -> BytesMut {
+		let mut idxed = BytesMut::zeroed(self.width as usize * self.height as usize);
+
+		for (dst, rgba) in idxed.iter_mut().zip(pixels.chunks_exact(4)) {
+			*dst = nearest_palette_index(
+				palette,
+				rgba[2] as i16,
+				rgba[1] as i16,
+				rgba[0] as i16,
+			);
+		}
+
+		idxed
+	}
+
+	fn dither_ordered(&self, pixels: &[u8], palette: &[f64]) -> BytesMut {
+		let width = self.width as usize;
+		let height = self.height as usize;
+
+		let mut idxed = BytesMut::zeroed(width * height);
+		for y in 0..height {
+			for x in 0..width {
+				let pixel_idx = (y * width + x) * 4;
+				let bias = BAYER_4X4[y % 4][x % 4] - 8;
+
+				let bgr = [2usize, 1, 0].map(|channel| {
+					(pixels[pixel_idx + channel] as i16 + bias).clamp(0, 255)
+				});
+
+				idxed[y * width + x] =
+					nearest_palette_index(palette, bgr[0], bgr[1], bgr[2]);
+			}
+		}
+
+		idxed
+	}
+
+	// Serpentine Floyd-Steinberg error diffusion against `palette`. Error is
+	// tracked as two rows of i16 per channel rather than a full frame buffer,
+	// and the diffusion offsets mirror on right-to-left rows.
+	fn dither_error_diffusion(&self, pixels: &[u8], palette: &[f64]) -> BytesMut {
+		let width = self.width as usize;
+		let height = self.height as usize;
+
+		let mut idxed = BytesMut::zeroed(width * height);
+		let mut current_errors = vec![[0i16; 3]; width];
+		let mut next_errors = vec![[0i16; 3]; width];
+
+		for y in 0..height {
+			let left_to_right = y % 2 == 0;
+			let xs: Box<dyn Iterator<Item = usize>> = if left_to_right {
+				Box::new(0..width)
+			} else {
+				Box::new((0..width).rev())
+			};
+
+			for x in xs {
+				let pixel_idx = (y * width + x) * 4;
+				let mut adjusted = [0i16; 3];
+				for (c, &channel) in [2usize, 1, 0].iter().enumerate() {
+					adjusted[c] = (pixels[pixel_idx + channel] as i16
+						+ current_errors[x][c])
+						.clamp(0, 255);
+				}
+
+				let index = nearest_palette_index(
+					palette,
+					adjusted[0],
+					adjusted[1],
+					adjusted[2],
+				);
+				idxed[y * width + x] = index;
+
+				let chosen = &palette[index as usize * 3..index as usize * 3 + 3];
+				let residual = [
+					adjusted[0] - chosen[0] as i16,
+					adjusted[1] - chosen[1] as i16,
+					adjusted[2] - chosen[2] as i16,
+				];
+
+				let (fwd, back) = if left_to_right { (1isize, -1isize) } else { (-1isize, 1isize) };
+				let x = x as isize;
+				add_diffused_error(&mut current_errors, x + fwd, width, residual, 7);
+				add_diffused_error(&mut next_errors, x + back, width, residual, 3);
+				add_diffused_error(&mut next_errors, x, width, residual, 5);
+				add_diffused_error(&mut next_errors, x + fwd, width, residual, 1);
+			}
+
+			std::mem::swap(&mut current_errors, &mut next_errors);
+			next_errors.iter_mut().for_each(|e| *e = [0; 3]);
+		}
+
+		idxed
+	}
 }
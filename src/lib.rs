@@ -0,0 +1,9 @@
+mod gif_encoder;
+mod lzw_encoder;
+mod neuquant;
+mod palette;
+
+pub use gif_encoder::*;
+pub use lzw_encoder::*;
+pub use neuquant::*;
+pub use palette::*;
@@ -88,7 +88,7 @@ impl LZWEncoder {
 		}
 	}
 
-	pub fn encode(&mut self, output: BytesMut) {
+	pub fn encode(&mut self, output: &mut BytesMut) {
 		output.put_u8(self.init_code_size);
 		self.remaining = self.width as u32 * self.height as u32;
 		self.current_pixel = 0;
@@ -96,7 +96,7 @@ impl LZWEncoder {
 		output.put_u8(0);
 	}
 
-	fn compress(&mut self, init_bits: usize, output: BytesMut) {
+	fn compress(&mut self, init_bits: usize, output: &mut BytesMut) {
 		self.global_initial_bits = init_bits;
 		self.clear_flag = false;
 		self.bit_size = self.global_initial_bits;
@@ -107,30 +107,26 @@ impl LZWEncoder {
 		self.acc = 0;
 
 		let mut code = self.next_pixel();
-		let mut hash = 80048;
 		let hash_shift = 4;
 		self.reset_hash_range(HASH_SIZE);
-		self.proc_output(self.clear_code, output);
+		self.proc_output(self.clear_code, &mut *output);
 
 		let mut c = self.next_pixel();
-		'outer: while c != EOF as u8 {
-			hash = ((c << BITS) + code) as i32;
+		'outer: while c != EOF {
+			let hash = (c << BITS) + code;
 
 			let mut i = ((c << hash_shift) ^ code) as usize;
 			if self.hashes[i] == hash {
-				code = self.codes[i] as u8;
+				code = self.codes[i];
 				continue;
 			}
 
 			if self.hashes[i] >= 0 {
-				let mut dispose = if i == 0 { 1 } else { HASH_SIZE - i };
+				let dispose = if i == 0 { 1 } else { HASH_SIZE - i };
 				loop {
-					i -= dispose;
-					if i < 0 {
-						i += HASH_SIZE;
-					}
+					i = (i + HASH_SIZE - dispose) % HASH_SIZE;
 					if self.hashes[i] == hash {
-						code = self.codes[i] as u8;
+						code = self.codes[i];
 						continue 'outer;
 					}
 					if !self.hashes[i] >= 0 {
@@ -139,24 +135,24 @@ impl LZWEncoder {
 				}
 			}
 
-			self.proc_output(code as usize, output);
+			self.proc_output(code as usize, &mut *output);
 			code = c;
 			if self.first_unused_entry < 1 << BITS {
 				self.codes[i] = self.first_unused_entry as i32;
 				self.first_unused_entry += 1;
 				self.hashes[i] = hash;
 			} else {
-				self.clear_code_table(output);
+				self.clear_code_table(&mut *output);
 			}
 
 			c = self.next_pixel();
 		}
 
-		self.proc_output(code as usize, output);
+		self.proc_output(code as usize, &mut *output);
 		self.proc_output(self.end_of_frame_code, output);
 	}
 
-	fn add_char(&mut self, c: char, output: BytesMut) {
+	fn add_char(&mut self, c: char, output: &mut BytesMut) {
 		self.accs[self.acc as usize] = c as u8;
 		self.acc += 1;
 		if self.acc >= 254 {
@@ -164,7 +160,7 @@ impl LZWEncoder {
 		}
 	}
 
-	fn clear_code_table(&mut self, output: BytesMut) {
+	fn clear_code_table(&mut self, output: &mut BytesMut) {
 		self.reset_hash_range(HASH_SIZE);
 		self.first_unused_entry = self.clear_code + 2;
 		self.clear_flag = true;
@@ -177,11 +173,10 @@ impl LZWEncoder {
 	}
 
 	#[inline]
-	fn flush_packet(&mut self, output: BytesMut) {
+	fn flush_packet(&mut self, output: &mut BytesMut) {
 		if self.acc > 0 {
 			output.put_u8(self.acc as u8);
-			output[..self.acc as usize]
-				.copy_from_slice(&self.accs[..self.acc as usize]);
+			output.put_slice(&self.accs[..self.acc as usize]);
 			self.acc = 0;
 		}
 	}
@@ -191,18 +186,18 @@ impl LZWEncoder {
 		(1 << size) - 1
 	}
 
-	fn next_pixel(&mut self) -> u8 {
+	fn next_pixel(&mut self) -> i32 {
 		if self.remaining == 0 {
-			EOF as u8
+			EOF
 		} else {
 			self.remaining -= 1;
 			let pixel = self.pixels[self.current_pixel];
 			self.current_pixel += 1;
-			pixel & 0xff
+			(pixel & 0xff) as i32
 		}
 	}
 
-	fn proc_output(&mut self, code: usize, output: BytesMut) {
+	fn proc_output(&mut self, code: usize, output: &mut BytesMut) {
 		self.current_acc &= MASKS[self.current_bits] as usize;
 		self.current_acc = if self.current_bits > 0 {
 			self.current_acc |= code << self.current_bits;
@@ -214,7 +209,7 @@ impl LZWEncoder {
 		self.current_bits += self.bit_size;
 
 		while self.current_bits >= 8 {
-			self.add_char((self.current_acc as u8 & 0xff) as char, output);
+			self.add_char((self.current_acc as u8 & 0xff) as char, &mut *output);
 			self.current_acc >>= 8;
 			self.current_bits -= 8;
 		}
@@ -236,7 +231,7 @@ impl LZWEncoder {
 
 		if code == self.end_of_frame_code {
 			while self.current_bits >= 0 {
-				self.add_char((self.current_acc as u8 & 0xff) as char, output);
+				self.add_char((self.current_acc as u8 & 0xff) as char, &mut *output);
 				self.current_acc >>= 8;
 				self.current_bits -= 8;
 			}
@@ -244,3 +239,115 @@ impl LZWEncoder {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Minimal variable-bit-width LZW reader over the GIF sub-block framing
+	// `LZWEncoder::encode` writes, enough to round-trip what we feed it
+	// without pulling in a full GIF decoding dependency.
+	fn decode(data: &[u8]) -> Vec<u8> {
+		let min_code_size = data[0] as usize;
+		let clear_code = 1usize << min_code_size;
+		let eoi_code = clear_code + 1;
+
+		let mut bits = Vec::new();
+		let mut pos = 1;
+		while pos < data.len() {
+			let block_len = data[pos] as usize;
+			pos += 1;
+			if block_len == 0 {
+				break;
+			}
+			for &byte in &data[pos..pos + block_len] {
+				for bit in 0..8 {
+					bits.push((byte >> bit) & 1);
+				}
+			}
+			pos += block_len;
+		}
+
+		let mut bit_pos = 0;
+		let mut read_code = |size: usize| -> usize {
+			let mut code = 0usize;
+			for (i, &bit) in bits[bit_pos..bit_pos + size].iter().enumerate() {
+				code |= (bit as usize) << i;
+			}
+			bit_pos += size;
+			code
+		};
+
+		let fresh_table = |clear_code: usize| -> Vec<Vec<u8>> {
+			let mut table: Vec<Vec<u8>> = (0..clear_code).map(|c| vec![c as u8]).collect();
+			table.push(vec![]); // clear code
+			table.push(vec![]); // end-of-information code
+			table
+		};
+
+		let mut table = fresh_table(clear_code);
+		let mut code_size = min_code_size + 1;
+		let mut max_code = (1usize << code_size) - 1;
+		let mut prev: Option<Vec<u8>> = None;
+		let mut output = Vec::new();
+
+		loop {
+			let code = read_code(code_size);
+			if code == clear_code {
+				table = fresh_table(clear_code);
+				code_size = min_code_size + 1;
+				max_code = (1usize << code_size) - 1;
+				prev = None;
+				continue;
+			}
+			if code == eoi_code {
+				break;
+			}
+
+			let entry = if code < table.len() {
+				table[code].clone()
+			} else if let Some(p) = &prev {
+				let mut e = p.clone();
+				e.push(p[0]);
+				e
+			} else {
+				break;
+			};
+
+			output.extend_from_slice(&entry);
+
+			if let Some(p) = &prev {
+				let mut new_entry = p.clone();
+				new_entry.push(entry[0]);
+				table.push(new_entry);
+				if table.len() > max_code && code_size < BITS as usize {
+					code_size += 1;
+					max_code = (1usize << code_size) - 1;
+				}
+			}
+
+			prev = Some(entry);
+		}
+
+		output
+	}
+
+	#[test]
+	fn encoded_stream_round_trips_back_to_the_source_indices() {
+		let width = 6u16;
+		let height = 2u16;
+		let color_depth = 2u8; // 4-color palette
+		let pixels: Vec<u8> = vec![0, 1, 2, 3, 3, 2, 1, 0, 0, 0, 1, 1];
+
+		let mut encoder = LZWEncoder::new(
+			width,
+			height,
+			BytesMut::from(pixels.as_slice()),
+			color_depth,
+		);
+		let mut output = BytesMut::new();
+		encoder.encode(&mut output);
+
+		assert_eq!(decode(&output), pixels);
+	}
+}
@@ -0,0 +1,409 @@
+//! Alternative palette backends: median-cut and ELBG
+//!
+//! [`NeuQuant`](crate::neuquant::NeuQuant) is a neural-net quantizer tuned for
+//! quality on photographic frames, but it is comparatively slow. These two
+//! backends trade off against it:
+//!
+//! - [`MedianCut`] is a cheap, deterministic box-splitting quantizer.
+//! - [`Elbg`] refines a starting codebook (typically a [`MedianCut`] result)
+//!   with Linde-Buzo-Gray iteration plus the "enhanced" utility-driven
+//!   codeword shuffle described in Orchard & Bouman.
+//!
+//! Both expose a `get_color_map` returning the same `[f64; 256 * 3]` shape
+//! produced by [`NeuQuant::get_color_map`](crate::neuquant::NeuQuant::get_color_map),
+//! so `GifEncoder` can swap between backends without touching the rest of
+//! the pipeline. Colors are stored and returned as `[b, g, r]` triples to
+//! match that convention.
+
+use std::collections::HashMap;
+
+use derivative::Derivative;
+use napi_derive::napi;
+
+const MAX_COLORS: usize = 256;
+const ELBG_EPSILON: f64 = 0.01;
+const ELBG_MAX_ITERS: usize = 30;
+const ELBG_ENHANCE_PASSES: usize = 4;
+// Roughly how many pixels the codebook-growth loop samples per iteration.
+// Growth re-runs a full `run_lbg` convergence after every single codeword it
+// adds, so left unsampled it costs O(MAX_COLORS * ELBG_MAX_ITERS * pixels).
+const ELBG_GROWTH_SAMPLE_TARGET: usize = 4096;
+
+/// Which palette-generation backend `GifEncoder` should use for a frame.
+#[napi]
+#[derive(Derivative, Clone, Copy, PartialEq, Eq)]
+#[derivative(Default)]
+pub enum Quantizer {
+	#[derivative(Default)]
+	NeuQuant,
+	MedianCut,
+	Elbg,
+}
+
+struct ColorBox {
+	// (b, g, r) -> population
+	colors: Vec<([u8; 3], u32)>,
+}
+
+impl ColorBox {
+	fn population(&self) -> u64 {
+		self.colors.iter().map(|&(_, n)| n as u64).sum()
+	}
+
+	fn bounds(&self) -> ([u8; 3], [u8; 3]) {
+		let mut min = [u8::MAX; 3];
+		let mut max = [0u8; 3];
+		for &(color, _) in &self.colors {
+			for i in 0..3 {
+				min[i] = min[i].min(color[i]);
+				max[i] = max[i].max(color[i]);
+			}
+		}
+		(min, max)
+	}
+
+	fn longest_axis(&self) -> usize {
+		let (min, max) = self.bounds();
+		let extent = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+		(0..3).max_by_key(|&i| extent[i]).unwrap()
+	}
+
+	// Extent on the longest axis weighted by population, used to rank which
+	// box to split next.
+	fn split_priority(&self) -> u64 {
+		let (min, max) = self.bounds();
+		let axis = self.longest_axis();
+		(max[axis] - min[axis]) as u64 * self.population()
+	}
+
+	fn is_splittable(&self) -> bool {
+		self.colors.len() > 1
+	}
+
+	fn average_color(&self) -> [f64; 3] {
+		let population = self.population().max(1) as f64;
+		let mut sum = [0f64; 3];
+		for &(color, n) in &self.colors {
+			for i in 0..3 {
+				sum[i] += color[i] as f64 * n as f64;
+			}
+		}
+		sum.map(|c| c / population)
+	}
+
+	fn split(mut self) -> (ColorBox, ColorBox) {
+		let axis = self.longest_axis();
+		self.colors.sort_by_key(|&(color, _)| color[axis]);
+
+		let total = self.population();
+		let half = total / 2;
+		let mut running = 0u64;
+		let mut split_at = self.colors.len();
+		for (i, &(_, n)) in self.colors.iter().enumerate() {
+			running += n as u64;
+			if running >= half {
+				split_at = i + 1;
+				break;
+			}
+		}
+		// Keep both halves non-empty even on a lopsided population.
+		let split_at = split_at.clamp(1, self.colors.len() - 1);
+
+		let right = self.colors.split_off(split_at);
+		(ColorBox { colors: self.colors }, ColorBox { colors: right })
+	}
+}
+
+fn histogram(pixels: &[u8]) -> Vec<([u8; 3], u32)> {
+	let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+	for triple in pixels.chunks_exact(3) {
+		*counts.entry([triple[0], triple[1], triple[2]]).or_insert(0) += 1;
+	}
+	let mut colors: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+	colors.sort_unstable();
+	colors
+}
+
+fn color_map_from_boxes(boxes: &[ColorBox]) -> [f64; MAX_COLORS * 3] {
+	let mut map = [0f64; MAX_COLORS * 3];
+	for (chunk, color_box) in map.chunks_exact_mut(3).zip(boxes) {
+		chunk.copy_from_slice(&color_box.average_color());
+	}
+	map
+}
+
+/// Deterministic box-splitting quantizer (a.k.a. Heckbert's median-cut).
+#[napi]
+pub struct MedianCut {
+	pixels: Vec<u8>,
+	max_colors: u16,
+}
+
+#[napi]
+impl MedianCut {
+	pub fn new(pixels: Vec<u8>, max_colors: u16) -> Self {
+		MedianCut { pixels, max_colors: max_colors.min(MAX_COLORS as u16) }
+	}
+
+	pub fn get_color_map(&self) -> [f64; MAX_COLORS * 3] {
+		color_map_from_boxes(&self.split_boxes())
+	}
+
+	/// Number of distinct colors actually produced, before
+	/// [`Self::get_color_map`] pads the result out to a fixed `MAX_COLORS`
+	/// shape. Useful for seeding [`Elbg`] without wasting codewords on
+	/// duplicate black padding.
+	pub fn color_count(&self) -> u32 {
+		self.split_boxes().len() as u32
+	}
+
+	fn split_boxes(&self) -> Vec<ColorBox> {
+		let colors = histogram(&self.pixels);
+		if colors.is_empty() {
+			return Vec::new();
+		}
+
+		let mut boxes = vec![ColorBox { colors }];
+		while boxes.len() < self.max_colors as usize {
+			let Some((split_idx, _)) = boxes
+				.iter()
+				.enumerate()
+				.filter(|(_, b)| b.is_splittable())
+				.max_by_key(|(_, b)| b.split_priority())
+			else {
+				break;
+			};
+
+			let target = boxes.swap_remove(split_idx);
+			let (a, b) = target.split();
+			boxes.push(a);
+			boxes.push(b);
+		}
+
+		boxes
+	}
+}
+
+/// Linde-Buzo-Gray quantizer with the "enhanced" utility-driven codeword
+/// shuffle (Orchard & Bouman's ELBG).
+///
+/// Seeded from an initial codebook (a [`MedianCut`] color map works well) and
+/// refined against the full pixel set.
+#[napi]
+pub struct Elbg {
+	pixels: Vec<u8>,
+	codebook: Vec<[f64; 3]>,
+}
+
+#[napi]
+impl Elbg {
+	/// `initial_color_count` is how many of `initial_color_map`'s entries are
+	/// real colors rather than a quantizer's fixed-size padding (see
+	/// [`MedianCut::color_count`]) — seeding from the padding would waste the
+	/// whole codebook on duplicate black centroids.
+	pub fn new(
+		pixels: Vec<u8>,
+		initial_color_map: Vec<f64>,
+		initial_color_count: u32,
+	) -> Self {
+		let count = (initial_color_count as usize).clamp(1, MAX_COLORS);
+		let codebook = initial_color_map
+			.chunks_exact(3)
+			.take(count)
+			.map(|c| [c[0], c[1], c[2]])
+			.collect();
+		Elbg { pixels, codebook }
+	}
+
+	pub fn get_color_map(&self) -> [f64; MAX_COLORS * 3] {
+		let colors: Vec<[f64; 3]> = self
+			.pixels
+			.chunks_exact(3)
+			.map(|c| [c[0] as f64, c[1] as f64, c[2] as f64])
+			.collect();
+		if colors.is_empty() {
+			return [0f64; MAX_COLORS * 3];
+		}
+
+		let mut codebook = self.codebook.clone();
+		if codebook.is_empty() {
+			codebook.push(colors[0]);
+		}
+
+		run_lbg(&colors, &mut codebook);
+
+		// Grow toward a full MAX_COLORS-entry table by repeatedly splitting
+		// the highest-distortion cell, rather than seeding with duplicate
+		// black centroids the way padding the codebook up front would —
+		// keeps every codeword meaningful even for frames with far fewer
+		// than MAX_COLORS real colors. Each addition re-converges the whole
+		// codebook, so — the same accuracy-for-speed trade NeuQuant's
+		// `sample_factorial` and `quantization_distortion`'s `stride` make —
+		// growth runs against a subsample and only the final passes below
+		// see every pixel.
+		let growth_colors = subsample(&colors, ELBG_GROWTH_SAMPLE_TARGET);
+		while codebook.len() < MAX_COLORS && codebook.len() < colors.len() {
+			grow_codebook(&growth_colors, &mut codebook);
+			run_lbg(&growth_colors, &mut codebook);
+		}
+		run_lbg(&colors, &mut codebook);
+
+		for _ in 0..ELBG_ENHANCE_PASSES {
+			enhance(&colors, &mut codebook);
+		}
+
+		let mut map = [0f64; MAX_COLORS * 3];
+		for (chunk, color) in map.chunks_exact_mut(3).zip(&codebook) {
+			chunk.copy_from_slice(color);
+		}
+		map
+	}
+}
+
+// Strides down to roughly `target` colors, the same accuracy-for-speed trade
+// NeuQuant's `sample_factorial` and `quantization_distortion`'s `stride` make.
+fn subsample(colors: &[[f64; 3]], target: usize) -> Vec<[f64; 3]> {
+	let stride = (colors.len() / target.max(1)).max(1);
+	colors.iter().step_by(stride).copied().collect()
+}
+
+// Runs Linde-Buzo-Gray iteration (assign to nearest centroid, recompute
+// centroids from the assignment) to convergence and returns the final total
+// distortion.
+fn run_lbg(colors: &[[f64; 3]], codebook: &mut Vec<[f64; 3]>) -> f64 {
+	let mut distortion = f64::INFINITY;
+	for _ in 0..ELBG_MAX_ITERS {
+		let (assignments, cell_distortion) = assign_nearest(colors, codebook);
+		*codebook = recompute_centroids(colors, &assignments, codebook);
+
+		let total_distortion: f64 = cell_distortion.iter().sum();
+		if distortion - total_distortion < ELBG_EPSILON * distortion.max(1.0) {
+			distortion = total_distortion;
+			break;
+		}
+		distortion = total_distortion;
+	}
+	distortion
+}
+
+// Adds one more codeword to `codebook` by nudging a copy of the
+// highest-distortion cell's centroid, so the next `run_lbg` pass can split
+// that cell in two.
+fn grow_codebook(colors: &[[f64; 3]], codebook: &mut Vec<[f64; 3]>) {
+	let (_, cell_distortion) = assign_nearest(colors, codebook);
+	let Some((worst_cell, _)) = cell_distortion
+		.iter()
+		.enumerate()
+		.max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+	else {
+		return;
+	};
+
+	let mut new_centroid = codebook[worst_cell];
+	for c in new_centroid.iter_mut() {
+		*c = (*c + 1.0).min(255.0);
+	}
+	codebook.push(new_centroid);
+}
+
+fn nearest_centroid(color: &[f64; 3], codebook: &[[f64; 3]]) -> (usize, f64) {
+	codebook
+		.iter()
+		.enumerate()
+		.map(|(i, centroid)| {
+			let distance = (0..3)
+				.map(|c| (centroid[c] - color[c]).powi(2))
+				.sum::<f64>();
+			(i, distance)
+		})
+		.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+		.unwrap()
+}
+
+fn assign_nearest(
+	colors: &[[f64; 3]],
+	codebook: &[[f64; 3]],
+) -> (Vec<usize>, Vec<f64>) {
+	let mut assignments = Vec::with_capacity(colors.len());
+	let mut cell_distortion = vec![0f64; codebook.len()];
+
+	for color in colors {
+		let (cell, distance) = nearest_centroid(color, codebook);
+		assignments.push(cell);
+		cell_distortion[cell] += distance;
+	}
+
+	(assignments, cell_distortion)
+}
+
+fn recompute_centroids(
+	colors: &[[f64; 3]],
+	assignments: &[usize],
+	previous: &[[f64; 3]],
+) -> Vec<[f64; 3]> {
+	let mut sums = vec![[0f64; 3]; previous.len()];
+	let mut counts = vec![0u32; previous.len()];
+
+	for (&color, &cell) in colors.iter().zip(assignments) {
+		for i in 0..3 {
+			sums[cell][i] += color[i];
+		}
+		counts[cell] += 1;
+	}
+
+	sums.into_iter()
+		.zip(counts)
+		.enumerate()
+		.map(|(i, (sum, count))| {
+			if count == 0 {
+				// Empty cell: keep the previous centroid rather than producing NaN.
+				previous[i]
+			} else {
+				sum.map(|c| c / count as f64)
+			}
+		})
+		.collect()
+}
+
+// Enhanced ELBG utility step: try moving a low-utility codeword next to the
+// highest-distortion cell, keeping the move only if it lowers global
+// distortion.
+fn enhance(colors: &[[f64; 3]], codebook: &mut Vec<[f64; 3]>) {
+	let (_, cell_distortion) = assign_nearest(colors, codebook);
+	let distortion: f64 = cell_distortion.iter().sum();
+	let mean_distortion = cell_distortion.iter().sum::<f64>() / codebook.len().max(1) as f64;
+	if mean_distortion == 0.0 {
+		return;
+	}
+
+	let Some((low_utility_cell, _)) = cell_distortion
+		.iter()
+		.enumerate()
+		.map(|(i, &d)| (i, d / mean_distortion))
+		.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+	else {
+		return;
+	};
+	let Some((high_distortion_cell, _)) = cell_distortion
+		.iter()
+		.enumerate()
+		.max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+	else {
+		return;
+	};
+	if low_utility_cell == high_distortion_cell {
+		return;
+	}
+
+	let previous = codebook.clone();
+	let jittered = codebook[high_distortion_cell]
+		.map(|c| c * 1.001 + 1e-3);
+	codebook[low_utility_cell] = jittered;
+
+	let (_, new_cell_distortion) = assign_nearest(colors, codebook);
+	let new_distortion: f64 = new_cell_distortion.iter().sum();
+
+	if new_distortion >= distortion {
+		*codebook = previous;
+	}
+}